@@ -1,8 +1,250 @@
 //! This is a little command line utility to post process drawing scans.
 
-use clap::{command, arg, value_parser, Command, ArgMatches};
-use std::path::PathBuf;
+use clap::{command, arg, value_parser, Command, ArgMatches, ValueEnum};
+use std::path::{Path, PathBuf};
 use glam::Vec2;
+use image::ImageEncoder;
+use rayon::prelude::*;
+
+
+/// Method used to turn a color pixel into a single gray value before
+/// thresholding/halftoning. The naive luma conversions used by the `image`
+/// crate operate in gamma-encoded sRGB and can threshold inconsistently
+/// depending on the original hue, so these modes linearize first.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Luminance {
+    /// Linear Rec.709 luminance `Y`, rescaled to 0..255.
+    Rec709,
+    /// CIELAB lightness `L*` computed from linear Rec.709 luminance.
+    Cielab,
+    /// Oklab lightness `L` computed from linear sRGB.
+    Oklab,
+}
+
+impl Luminance {
+    fn gray(self, [r, g, b]: [u8; 3]) -> u8 {
+
+        fn linearize(c: u8) -> f32 {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+
+        let (r, g, b) = (linearize(r), linearize(g), linearize(b));
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+        let value = match self {
+            Luminance::Rec709 => y,
+            Luminance::Cielab => {
+                let l = if y <= 0.008856 { 903.3 * y } else { 116.0 * y.cbrt() - 16.0 };
+                l / 100.0
+            }
+            Luminance::Oklab => {
+                let l = 0.4122215 * r + 0.5363325 * g + 0.0514460 * b;
+                let m = 0.2119035 * r + 0.6806995 * g + 0.1073970 * b;
+                let s = 0.0883025 * r + 0.2817188 * g + 0.6299787 * b;
+                let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+                0.2104543 * l + 0.7936178 * m - 0.0040720 * s
+            }
+        };
+
+        (value.clamp(0.0, 1.0) * 255.0) as u8
+
+    }
+}
+
+
+/// Size of the tiled Bayer matrix used for ordered dithering.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BayerSize {
+    #[value(name = "2")]
+    Two,
+    #[value(name = "4")]
+    Four,
+    #[value(name = "8")]
+    Eight,
+}
+
+const BAYER_2: [u8; 4] = [
+    0, 2,
+    3, 1,
+];
+
+const BAYER_4: [u8; 16] = [
+    0, 8, 2, 10,
+    12, 4, 14, 6,
+    3, 11, 1, 9,
+    15, 7, 13, 5,
+];
+
+const BAYER_8: [u8; 64] = [
+    0, 48, 12, 60, 3, 51, 15, 63,
+    32, 16, 44, 28, 35, 19, 47, 31,
+    8, 56, 4, 52, 11, 59, 7, 55,
+    40, 24, 36, 20, 43, 27, 39, 23,
+    2, 50, 14, 62, 1, 49, 13, 61,
+    34, 18, 46, 30, 33, 17, 45, 29,
+    10, 58, 6, 54, 9, 57, 5, 53,
+    42, 26, 38, 22, 41, 25, 37, 21,
+];
+
+impl BayerSize {
+
+    /// Normalized threshold (0..1) of the tiled matrix at the given pixel
+    /// coordinate, to be compared against a normalized gray value.
+    fn threshold(self, x: u32, y: u32) -> f32 {
+        let (n, table): (u32, &[u8]) = match self {
+            BayerSize::Two => (2, &BAYER_2),
+            BayerSize::Four => (4, &BAYER_4),
+            BayerSize::Eight => (8, &BAYER_8),
+        };
+        let value = table[((y % n) * n + (x % n)) as usize];
+        (value as f32 + 0.5) / (n * n) as f32
+    }
+
+}
+
+
+/// Bits per pixel of a saved bilevel PNG.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BitDepth {
+    #[value(name = "1")]
+    One,
+    #[value(name = "8")]
+    Eight,
+}
+
+/// PNG deflate compression effort, forwarded to whichever PNG encoder ends
+/// up writing the file.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PngCompression {
+    Default,
+    Fast,
+    Best,
+}
+
+impl From<PngCompression> for image::codecs::png::CompressionType {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Default => image::codecs::png::CompressionType::Default,
+            PngCompression::Fast => image::codecs::png::CompressionType::Fast,
+            PngCompression::Best => image::codecs::png::CompressionType::Best,
+        }
+    }
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+/// PNG row filtering strategy.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+impl From<PngFilter> for image::codecs::png::FilterType {
+    fn from(value: PngFilter) -> Self {
+        match value {
+            PngFilter::None => image::codecs::png::FilterType::NoFilter,
+            PngFilter::Sub => image::codecs::png::FilterType::Sub,
+            PngFilter::Up => image::codecs::png::FilterType::Up,
+            PngFilter::Avg => image::codecs::png::FilterType::Avg,
+            PngFilter::Paeth => image::codecs::png::FilterType::Paeth,
+            PngFilter::Adaptive => image::codecs::png::FilterType::Adaptive,
+        }
+    }
+}
+
+/// Maps `PngFilter` onto the `png` crate's two separate knobs: a concrete
+/// per-scanline `FilterType` fallback, and whether adaptive filter
+/// selection (`AdaptiveFilterType`) is enabled. Unlike `image`'s filter
+/// enum, the `png` crate has no single "Adaptive" `FilterType` variant, so
+/// `Adaptive` keeps `Sub` as its non-adaptive fallback and turns the
+/// heuristic on instead.
+fn png_filter_settings(filter: PngFilter) -> (png::FilterType, png::AdaptiveFilterType) {
+    match filter {
+        PngFilter::None => (png::FilterType::NoFilter, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
+        PngFilter::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive),
+    }
+}
+
+/// Save a grayscale image as PNG, honoring the requested bit depth.
+///
+/// At 8 bits this just delegates to `image`'s own PNG encoder. At 1 bit,
+/// `image` has no public way to emit sub-byte color types (its encoder only
+/// accepts 8/16-bit color types), so pixels are packed MSB-first by hand and
+/// written with the `png` crate directly. `cutoff` is the pixel value at or
+/// above which a pixel is packed as a set (white) bit; callers must derive
+/// it from whatever actually separates black from white in their image,
+/// since that is not always exactly 128.
+fn save_gray_png(
+    image: &image::GrayImage,
+    out_path: &Path,
+    bit_depth: BitDepth,
+    compression: PngCompression,
+    filter: PngFilter,
+    cutoff: u8,
+) -> anyhow::Result<()> {
+
+    let file = std::fs::File::create(out_path)?;
+
+    match bit_depth {
+        BitDepth::Eight => {
+            let encoder = image::codecs::png::PngEncoder::new_with_quality(file, compression.into(), filter.into());
+            encoder.write_image(image.as_raw(), image.width(), image.height(), image::ExtendedColorType::L8)?;
+        }
+        BitDepth::One => {
+            let packed = pack_bits_msb(image, cutoff);
+            let mut encoder = png::Encoder::new(file, image.width(), image.height());
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::One);
+            encoder.set_compression(compression.into());
+            let (filter_type, adaptive_filter) = png_filter_settings(filter);
+            encoder.set_filter(filter_type);
+            encoder.set_adaptive_filter(adaptive_filter);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&packed)?;
+        }
+    }
+
+    Ok(())
+
+}
+
+/// Pack a thresholded grayscale image into MSB-first 1-bit-per-pixel rows,
+/// each padded to a byte boundary as required by the PNG format. A pixel
+/// value of `cutoff` or more becomes a set (white) bit.
+fn pack_bits_msb(image: &image::GrayImage, cutoff: u8) -> Vec<u8> {
+    let row_bytes = (image.width() as usize + 7) / 8;
+    let mut packed = vec![0u8; row_bytes * image.height() as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[0] >= cutoff {
+            let byte = y as usize * row_bytes + x as usize / 8;
+            let bit = 7 - (x as usize % 8);
+            packed[byte] |= 1 << bit;
+        }
+    }
+    packed
+}
 
 
 fn main() -> anyhow::Result<()> {
@@ -13,9 +255,10 @@ fn main() -> anyhow::Result<()> {
         .disable_version_flag(true)
         .subcommand(Command::new("bw")
             .about("Make an image black and white while erasing bright pixels and compressing black pixels range")
-            .arg(arg!(<PATH> "Path of image to post process")
+            .arg(arg!([PATH] "Path of image to post process, or a directory to batch process every file inside; not needed when --glob is given")
                 .id("in_path")
-                .value_parser(value_parser!(PathBuf)))
+                .value_parser(value_parser!(PathBuf))
+                .required_unless_present("glob"))
             .arg(arg!(-o --output <PATH> "Path of output image")
                 .id("out_path")
                 .value_parser(value_parser!(PathBuf)))
@@ -27,12 +270,36 @@ fn main() -> anyhow::Result<()> {
                 .default_value("0.4"))
             .arg(arg!(--base <BASE> "Base gray color for all black pixels after compression")
                 .value_parser(value_parser!(u8))
-                .default_value("20")))
+                .default_value("20"))
+            .arg(arg!(--luminance <MODE> "Perceptual gray conversion used before thresholding")
+                .value_parser(value_parser!(Luminance))
+                .default_value("rec709"))
+            .arg(arg!(--"bit-depth" <BITS> "Bits per pixel of the saved PNG, 1 packs the thresholded image into a true bilevel file")
+                .id("bit_depth")
+                .value_parser(value_parser!(BitDepth))
+                .default_value("8"))
+            .arg(arg!(--"png-compression" <LEVEL> "PNG deflate compression effort")
+                .id("png_compression")
+                .value_parser(value_parser!(PngCompression))
+                .default_value("default"))
+            .arg(arg!(--"png-filter" <FILTER> "PNG row filtering strategy")
+                .id("png_filter")
+                .value_parser(value_parser!(PngFilter))
+                .default_value("adaptive"))
+            .arg(arg!(--glob <PATTERN> "Batch process every file matching this glob pattern instead of the single PATH argument")
+                .id("glob")
+                .value_parser(value_parser!(String))
+                .required(false))
+            .arg(arg!(--jobs <N> "Worker threads used for batch processing, 0 uses one thread per available core")
+                .id("jobs")
+                .value_parser(value_parser!(usize))
+                .default_value("0")))
         .subcommand(Command::new("halftone")
             .about("Make and image black and white and make bright pixels transparent and create a halftone pattern from black pixels")
-            .arg(arg!(<PATH> "Path of image to post process")
+            .arg(arg!([PATH] "Path of image to post process, or a directory to batch process every file inside; not needed when --glob is given")
                 .id("in_path")
-                .value_parser(value_parser!(PathBuf)))
+                .value_parser(value_parser!(PathBuf))
+                .required_unless_present("glob"))
             .arg(arg!(-o --output <PATH> "Path of output image")
                 .id("out_path")
                 .value_parser(value_parser!(PathBuf)))
@@ -45,44 +312,182 @@ fn main() -> anyhow::Result<()> {
             .arg(arg!(--radius <RADIUS> "Radius of the circle")
                 .value_parser(value_parser!(f32))
                 .default_value("0.4"))
+            .arg(arg!(--angle <DEGREES> "Halftone screen rotation angle in degrees")
+                .value_parser(value_parser!(f32))
+                .default_value("45.0"))
             .arg(arg!(--base <BASE> "Base gray color for all pixels, halftone only applies to alpha channel")
                 .value_parser(value_parser!(u8))
-                .default_value("40")))
+                .default_value("40"))
+            .arg(arg!(--luminance <MODE> "Perceptual gray conversion used before thresholding")
+                .value_parser(value_parser!(Luminance))
+                .default_value("rec709"))
+            .arg(arg!(--color "Render a CMYK color halftone with a classic per-channel screen angle rosette instead of a single gray screen; --threshold, --angle, --base and --luminance do not apply, and it is ignored for .svg output"))
+            .arg(arg!(--"stride-c" <STRIDE> "Override stride for the cyan screen")
+                .id("stride_c")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"stride-m" <STRIDE> "Override stride for the magenta screen")
+                .id("stride_m")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"stride-y" <STRIDE> "Override stride for the yellow screen")
+                .id("stride_y")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"stride-k" <STRIDE> "Override stride for the black screen")
+                .id("stride_k")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"radius-c" <RADIUS> "Override dot radius for the cyan screen")
+                .id("radius_c")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"radius-m" <RADIUS> "Override dot radius for the magenta screen")
+                .id("radius_m")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"radius-y" <RADIUS> "Override dot radius for the yellow screen")
+                .id("radius_y")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--"radius-k" <RADIUS> "Override dot radius for the black screen")
+                .id("radius_k")
+                .value_parser(value_parser!(f32))
+                .required(false))
+            .arg(arg!(--glob <PATTERN> "Batch process every file matching this glob pattern instead of the single PATH argument")
+                .id("glob")
+                .value_parser(value_parser!(String))
+                .required(false))
+            .arg(arg!(--jobs <N> "Worker threads used for batch processing, 0 uses one thread per available core")
+                .id("jobs")
+                .value_parser(value_parser!(usize))
+                .default_value("0")))
+        .subcommand(Command::new("dither")
+            .about("Make an image 1-bit black and white using error-diffusion or ordered dithering")
+            .arg(arg!(<PATH> "Path of image to post process")
+                .id("in_path")
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(-o --output <PATH> "Path of output image")
+                .id("out_path")
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--threshold <THRESHOLD> "Gray threshold for error diffusion, pixels below are forced black, pixels at or above are forced white; not used with --ordered")
+                .value_parser(value_parser!(u8))
+                .default_value("128"))
+            .arg(arg!(--serpentine "Reverse scan direction on alternate rows to reduce directional dithering artifacts"))
+            .arg(arg!(--ordered <N> "Use ordered dithering against a tiled Bayer matrix instead of error diffusion")
+                .value_parser(value_parser!(BayerSize))
+                .required(false))
+            .arg(arg!(--"bit-depth" <BITS> "Bits per pixel of the saved PNG, 1 packs the dithered image into a true bilevel file")
+                .id("bit_depth")
+                .value_parser(value_parser!(BitDepth))
+                .default_value("8"))
+            .arg(arg!(--"png-compression" <LEVEL> "PNG deflate compression effort")
+                .id("png_compression")
+                .value_parser(value_parser!(PngCompression))
+                .default_value("default"))
+            .arg(arg!(--"png-filter" <FILTER> "PNG row filtering strategy")
+                .id("png_filter")
+                .value_parser(value_parser!(PngFilter))
+                .default_value("adaptive")))
         .get_matches();
 
     let (sub, sub_matches) = matches.remove_subcommand().unwrap();
     match &sub[..] {
         "bw" => bw(sub_matches),
         "halftone" => halftone(sub_matches),
+        "dither" => dither(sub_matches),
         _ => unreachable!()
     }
 
 }
 
+/// Options for `bw`, pulled out of `ArgMatches` once so a batch run can
+/// share a single parsed configuration across every file it processes.
+#[derive(Debug, Clone, Copy)]
+struct BwOptions {
+    threshold: u8,
+    compress: f32,
+    base: u8,
+    luminance: Luminance,
+    bit_depth: BitDepth,
+    /// Pixel value at or above which `--bit-depth 1` packs a set (white)
+    /// bit. Unused at 8 bits. Derived from `threshold`/`compress`/`base` so
+    /// it actually separates this run's black output from white, rather
+    /// than assuming a fixed midpoint like 128.
+    bit_depth_cutoff: u8,
+    png_compression: PngCompression,
+    png_filter: PngFilter,
+}
+
 fn bw(mut matches: ArgMatches) -> anyhow::Result<()> {
 
     let threshold = matches.remove_one::<u8>("threshold").unwrap();
-    let threshold_f32 = threshold as f32;
     let compress = matches.remove_one::<f32>("compress").unwrap();
     let base = matches.remove_one::<u8>("base").unwrap();
+    let bit_depth = matches.remove_one::<BitDepth>("bit_depth").unwrap();
 
-    let in_path = matches.remove_one::<PathBuf>("in_path").unwrap();
-    let out_path = matches.remove_one::<PathBuf>("out_path").unwrap_or_else(|| {
-        in_path.with_extension("bw.png")
-    });
+    let bit_depth_cutoff = if let BitDepth::One = bit_depth {
+        let max_black = ((threshold as f32 * compress) as u8).saturating_add(base);
+        if max_black == 255 {
+            anyhow::bail!(
+                "--bit-depth 1 needs compressed black pixels to stay below white (255), but --threshold {threshold} --compress {compress} --base {base} caps black at {max_black}"
+            );
+        }
+        max_black + 1
+    } else {
+        0
+    };
+
+    let options = BwOptions {
+        threshold,
+        compress,
+        base,
+        luminance: matches.remove_one::<Luminance>("luminance").unwrap(),
+        bit_depth,
+        bit_depth_cutoff,
+        png_compression: matches.remove_one::<PngCompression>("png_compression").unwrap(),
+        png_filter: matches.remove_one::<PngFilter>("png_filter").unwrap(),
+    };
+    let glob_pattern = matches.remove_one::<String>("glob");
+    let jobs = matches.remove_one::<usize>("jobs").unwrap();
+
+    let in_path = matches.remove_one::<PathBuf>("in_path");
+    let out_path = matches.remove_one::<PathBuf>("out_path");
+
+    if glob_pattern.is_none() && !in_path.as_deref().is_some_and(Path::is_dir) {
+        let in_path = in_path.expect("PATH is required unless --glob is given");
+        let out_path = out_path.unwrap_or_else(|| in_path.with_extension("bw.png"));
+        return bw_one(&in_path, &out_path, &options);
+    }
+
+    let inputs = collect_inputs(in_path.as_deref(), glob_pattern.as_deref())?;
+    run_batch(&inputs, "bw.png", jobs, |path, out| bw_one(path, out, &options))
+
+}
+
+fn bw_one(in_path: &Path, out_path: &Path, options: &BwOptions) -> anyhow::Result<()> {
+
+    let threshold_f32 = options.threshold as f32;
 
     println!("Opening image...");
     println!("  Path: {in_path:?}");
-    let mut image = image::open(&in_path)?.to_luma8();
+    let rgb_image = image::open(in_path)?.to_rgb8();
+    let mut image = image::ImageBuffer::from_fn(rgb_image.width(), rgb_image.height(), |x, y| {
+        image::Luma([options.luminance.gray(rgb_image.get_pixel(x, y).0)])
+    });
     println!("  Size: {}x{}", image.width(), image.height());
-    
+
     println!("Processing image...");
-    println!("  Threshold: {threshold}");
-    println!("  Compress: {compress}");
-    println!("  Base: {base}");
+    println!("  Threshold: {}", options.threshold);
+    println!("  Compress: {}", options.compress);
+    println!("  Base: {}", options.base);
+    println!("  Luminance: {:?}", options.luminance);
+    println!("  Bit depth: {:?}", options.bit_depth);
+    println!("  PNG compression: {:?}", options.png_compression);
+    println!("  PNG filter: {:?}", options.png_filter);
     for pixel in image.pixels_mut() {
-        if pixel[0] <= threshold {
-            pixel[0] = ((pixel[0] as f32 / threshold_f32 * compress * threshold_f32) as u8).saturating_add(base);
+        if pixel[0] <= options.threshold {
+            pixel[0] = ((pixel[0] as f32 / threshold_f32 * options.compress * threshold_f32) as u8).saturating_add(options.base);
         } else {
             pixel[0] = 255;
         }
@@ -90,65 +495,452 @@ fn bw(mut matches: ArgMatches) -> anyhow::Result<()> {
 
     println!("Saving image");
     println!("  Path: {out_path:?}");
-    image.save(&out_path)?;
+    save_gray_png(&image, out_path, options.bit_depth, options.png_compression, options.png_filter, options.bit_depth_cutoff)?;
 
     Ok(())
-    
+
+}
+
+/// Coverage (0..1) of the halftone dot for the screen cell containing
+/// `(x, y)`, given the screen rotation, the stride between dot centers and
+/// a base radius grown by the local ink `density` (0..1, darker is higher).
+fn halftone_coverage(x: f32, y: f32, angle: Vec2, stride: f32, radius: f32, density: f32) -> f32 {
+    let radius = radius + density;
+    let radius_squared = radius.powi(2);
+    let pos = angle.rotate(Vec2::new(x, y));
+    let index = (pos / stride).floor();
+    let delta_pos = pos - index * stride;
+    let delta = delta_pos / stride * 2.0 - 1.0;
+    let dist_squared = delta.length_squared();
+    (radius_squared - dist_squared).clamp(0.0, 1.0)
+}
+
+/// Options for `halftone`, pulled out of `ArgMatches` once so a batch run
+/// can share a single parsed configuration across every file it processes.
+#[derive(Debug, Clone, Copy)]
+struct HalftoneOptions {
+    threshold: u8,
+    stride: f32,
+    radius: f32,
+    angle_degrees: f32,
+    base: u8,
+    luminance: Luminance,
+    color: bool,
+    strides: [f32; 4],
+    radii: [f32; 4],
+}
+
+/// Default output file name for `halftone`, reused for both the single-file
+/// default and the per-file naming used in batch mode.
+fn halftone_extension(options: &HalftoneOptions) -> String {
+    format!("halftone_{}_{}_{}.png", options.stride, options.radius, options.base)
 }
 
 fn halftone(mut matches: ArgMatches) -> anyhow::Result<()> {
-    
-    let threshold = matches.remove_one::<u8>("threshold").unwrap();
+
     let stride = matches.remove_one::<f32>("stride").unwrap();
     let radius = matches.remove_one::<f32>("radius").unwrap();
-    let base = matches.remove_one::<u8>("base").unwrap();
+    let options = HalftoneOptions {
+        threshold: matches.remove_one::<u8>("threshold").unwrap(),
+        stride,
+        radius,
+        angle_degrees: matches.remove_one::<f32>("angle").unwrap(),
+        base: matches.remove_one::<u8>("base").unwrap(),
+        luminance: matches.remove_one::<Luminance>("luminance").unwrap(),
+        color: matches.remove_one::<bool>("color").unwrap_or(false),
+        strides: [
+            matches.remove_one::<f32>("stride_c").unwrap_or(stride),
+            matches.remove_one::<f32>("stride_m").unwrap_or(stride),
+            matches.remove_one::<f32>("stride_y").unwrap_or(stride),
+            matches.remove_one::<f32>("stride_k").unwrap_or(stride),
+        ],
+        radii: [
+            matches.remove_one::<f32>("radius_c").unwrap_or(radius),
+            matches.remove_one::<f32>("radius_m").unwrap_or(radius),
+            matches.remove_one::<f32>("radius_y").unwrap_or(radius),
+            matches.remove_one::<f32>("radius_k").unwrap_or(radius),
+        ],
+    };
+    let glob_pattern = matches.remove_one::<String>("glob");
+    let jobs = matches.remove_one::<usize>("jobs").unwrap();
+
+    let in_path = matches.remove_one::<PathBuf>("in_path");
+    let out_path = matches.remove_one::<PathBuf>("out_path");
+
+    if glob_pattern.is_none() && !in_path.as_deref().is_some_and(Path::is_dir) {
+        let in_path = in_path.expect("PATH is required unless --glob is given");
+        let out_path = out_path.unwrap_or_else(|| in_path.with_extension(halftone_extension(&options)));
+        return halftone_one(&in_path, &out_path, &options);
+    }
+
+    let inputs = collect_inputs(in_path.as_deref(), glob_pattern.as_deref())?;
+    let out_extension = halftone_extension(&options);
+    run_batch(&inputs, &out_extension, jobs, |path, out| halftone_one(path, out, &options))
+
+}
+
+fn halftone_one(in_path: &Path, out_path: &Path, options: &HalftoneOptions) -> anyhow::Result<()> {
+
+    println!("Opening image...");
+    println!("  Path: {in_path:?}");
+
+    println!("Processing image...");
+    println!("  Threshold: {}", options.threshold);
+    println!("  Stride: {}", options.stride);
+    println!("  Radius: {}", options.radius);
+    println!("  Angle: {}", options.angle_degrees);
+    println!("  Base: {}", options.base);
+    println!("  Luminance: {:?}", options.luminance);
+    println!("  Color: {}", options.color);
+
+    let is_svg = out_path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return halftone_svg(in_path, out_path, options.threshold, options.stride, options.radius, options.angle_degrees, options.base, options.luminance);
+    }
+
+    if options.color {
+        return halftone_color(in_path, out_path, options.strides, options.radii);
+    }
+
+    let rgba_image = image::open(in_path)?.to_rgba8();
+    let mut image = image::ImageBuffer::from_fn(rgba_image.width(), rgba_image.height(), |x, y| {
+        let pixel = rgba_image.get_pixel(x, y).0;
+        image::LumaA([options.luminance.gray([pixel[0], pixel[1], pixel[2]]), pixel[3]])
+    });
+    println!("  Size: {}x{}", image.width(), image.height());
+
+    let angle = Vec2::from_angle(options.angle_degrees.to_radians());
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+
+        if pixel[1] != 0 && pixel[0] <= options.threshold {
+            let density = 1.0 - pixel[0] as f32 / 255.0;
+            let coverage = halftone_coverage(x as f32, y as f32, angle, options.stride, options.radius, density);
+            pixel[1] = (coverage * 255.0) as u8;
+        } else {
+            pixel[1] = 0;
+        }
+
+        pixel[0] = options.base;
+
+    }
+
+    println!("Saving image");
+    println!("  Path: {out_path:?}");
+    image.save(out_path)?;
+
+    Ok(())
+
+}
+
+/// Screen angles of the classic CMYK rosette, in this channel order:
+/// cyan, magenta, yellow, black.
+const CMYK_ANGLES_DEG: [f32; 4] = [15.0, 75.0, 0.0, 45.0];
+
+fn halftone_color(
+    in_path: &Path,
+    out_path: &Path,
+    strides: [f32; 4],
+    radii: [f32; 4],
+) -> anyhow::Result<()> {
+
+    println!("Opening image...");
+    println!("  Path: {in_path:?}");
+    let rgb_image = image::open(in_path)?.to_rgb8();
+    println!("  Size: {}x{}", rgb_image.width(), rgb_image.height());
+
+    let angles: Vec<Vec2> = CMYK_ANGLES_DEG.iter()
+        .map(|deg| Vec2::from_angle(deg.to_radians()))
+        .collect();
+
+    let image = image::ImageBuffer::from_fn(rgb_image.width(), rgb_image.height(), |x, y| {
+
+        let [r, g, b] = rgb_image.get_pixel(x, y).0;
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+
+        let k = 1.0 - r.max(g).max(b);
+        let (c, m, ye) = if k >= 1.0 {
+            (0.0, 0.0, 0.0)
+        } else {
+            ((1.0 - r - k) / (1.0 - k), (1.0 - g - k) / (1.0 - k), (1.0 - b - k) / (1.0 - k))
+        };
+
+        let inks = [c, m, ye, k];
+        let mut coverage = [0.0f32; 4];
+        for channel in 0..4 {
+            // Mirror the grayscale path's zero-gate: without it, a radius
+            // large enough to cover a dot's cell still reports positive
+            // coverage even at zero ink density, printing a rosette on
+            // plain white.
+            if inks[channel] > 0.0 {
+                coverage[channel] = halftone_coverage(x as f32, y as f32, angles[channel], strides[channel], radii[channel], inks[channel]);
+            }
+        }
+
+        let out_r = 255.0 * (1.0 - coverage[0]) * (1.0 - coverage[3]);
+        let out_g = 255.0 * (1.0 - coverage[1]) * (1.0 - coverage[3]);
+        let out_b = 255.0 * (1.0 - coverage[2]) * (1.0 - coverage[3]);
+
+        image::Rgb([out_r as u8, out_g as u8, out_b as u8])
+
+    });
+
+    println!("Saving image");
+    println!("  Path: {out_path:?}");
+    image.save(out_path)?;
+
+    Ok(())
+
+}
+
+/// Vector backend for `halftone`: walks the halftone grid in rotated screen
+/// space and emits one `<circle>` per dot whose source pixel is at or below
+/// the threshold, transformed back into image space via the inverse rotation.
+/// This gives a resolution-independent halftone, handy for large-format
+/// printing. No external SVG crate is needed, the output is small enough
+/// to hand-serialize.
+fn halftone_svg(
+    in_path: &Path,
+    out_path: &Path,
+    threshold: u8,
+    stride: f32,
+    radius: f32,
+    angle_degrees: f32,
+    base: u8,
+    luminance: Luminance,
+) -> anyhow::Result<()> {
+
+    println!("Opening image...");
+    println!("  Path: {in_path:?}");
+    let rgba_image = image::open(in_path)?.to_rgba8();
+    let (width, height) = rgba_image.dimensions();
+    println!("  Size: {width}x{height}");
+
+    let angle = Vec2::from_angle(angle_degrees.to_radians());
+    let inv_angle = Vec2::from_angle(-angle_degrees.to_radians());
+
+    let corners = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(width as f32, 0.0),
+        Vec2::new(0.0, height as f32),
+        Vec2::new(width as f32, height as f32),
+    ];
+    let rotated_corners = corners.map(|corner| angle.rotate(corner));
+    let min = rotated_corners.into_iter().reduce(Vec2::min).unwrap();
+    let max = rotated_corners.into_iter().reduce(Vec2::max).unwrap();
+
+    let i_min = (min.x / stride).floor() as i32;
+    let i_max = (max.x / stride).ceil() as i32;
+    let j_min = (min.y / stride).floor() as i32;
+    let j_max = (max.y / stride).ceil() as i32;
+
+    let mut circles = String::new();
+
+    for j in j_min..=j_max {
+        for i in i_min..=i_max {
+
+            let cell_center = Vec2::new((i as f32 + 0.5) * stride, (j as f32 + 0.5) * stride);
+            let source = inv_angle.rotate(cell_center);
+
+            if source.x < 0.0 || source.y < 0.0 || source.x >= width as f32 || source.y >= height as f32 {
+                continue;
+            }
+
+            let pixel = rgba_image.get_pixel(source.x as u32, source.y as u32).0;
+            let lum = luminance.gray([pixel[0], pixel[1], pixel[2]]);
+
+            if pixel[3] == 0 || lum > threshold {
+                continue;
+            }
+
+            let dot_radius = radius + (1.0 - lum as f32 / 255.0);
+            circles.push_str(&format!(
+                "  <circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"rgb({base},{base},{base})\"/>\n",
+                source.x, source.y, dot_radius,
+            ));
+
+        }
+    }
+
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\" width=\"{width}\" height=\"{height}\">\n{circles}</svg>\n",
+    );
+
+    println!("Saving image");
+    println!("  Path: {out_path:?}");
+    std::fs::write(out_path, svg)?;
+
+    Ok(())
+
+}
+
+fn dither(mut matches: ArgMatches) -> anyhow::Result<()> {
+
+    let threshold = matches.remove_one::<u8>("threshold").unwrap() as f32;
+    let serpentine = matches.remove_one::<bool>("serpentine").unwrap_or(false);
+    let ordered = matches.remove_one::<BayerSize>("ordered");
+    let bit_depth = matches.remove_one::<BitDepth>("bit_depth").unwrap();
+    let png_compression = matches.remove_one::<PngCompression>("png_compression").unwrap();
+    let png_filter = matches.remove_one::<PngFilter>("png_filter").unwrap();
 
     let in_path = matches.remove_one::<PathBuf>("in_path").unwrap();
     let out_path = matches.remove_one::<PathBuf>("out_path").unwrap_or_else(|| {
-        in_path.with_extension(format!("halftone_{stride}_{radius}_{base}.png"))
+        in_path.with_extension("dither.png")
     });
 
     println!("Opening image...");
     println!("  Path: {in_path:?}");
-    let mut image = image::open(&in_path)?.to_luma_alpha8();
-    println!("  Size: {}x{}", image.width(), image.height());
+    let gray_image = image::open(&in_path)?.to_luma8();
+    let (width, height) = gray_image.dimensions();
+    println!("  Size: {width}x{height}");
 
     println!("Processing image...");
     println!("  Threshold: {threshold}");
-    println!("  Stride: {stride}");
-    println!("  Radius: {radius}");
-    println!("  Base: {base}");
+    println!("  Serpentine: {serpentine}");
+    println!("  Ordered: {ordered:?}");
+    println!("  Bit depth: {bit_depth:?}");
+    println!("  PNG compression: {png_compression:?}");
+    println!("  PNG filter: {png_filter:?}");
 
-    let angle = Vec2::from_angle(std::f32::consts::FRAC_PI_4);
-    // let radius_squared = radius.powi(2);
+    let mut image = image::GrayImage::new(width, height);
 
-    for (x, y, pixel) in image.enumerate_pixels_mut() {
-        
-        if pixel[1] != 0 && pixel[0] <= threshold {
-
-            let radius = radius + (1.0 - pixel[0] as f32 / 255.0);
-            let radius_squared = radius.powi(2);
-            
-            let pos = angle.rotate(Vec2::new(x as f32, y as f32));
-            let index = (pos / stride).floor();
-            let delta_pos = pos - index * stride;
-            let delta = delta_pos / stride * 2.0 - 1.0;
-            let dist_squared = delta.length_squared();
-            let alpha = (radius_squared - dist_squared).clamp(0.0, 1.0);
-            
-            pixel[1] = (alpha * 255.0) as u8;
+    if let Some(bayer) = ordered {
 
-        } else {
-            pixel[1] = 0;
+        for (x, y, pixel) in gray_image.enumerate_pixels() {
+            let gray = pixel[0] as f32 / 255.0;
+            let on = gray > bayer.threshold(x, y);
+            image.put_pixel(x, y, image::Luma([if on { 255 } else { 0 }]));
         }
 
-        pixel[0] = base;
+    } else {
+
+        let mut buf: Vec<f32> = gray_image.pixels().map(|pixel| pixel[0] as f32).collect();
+
+        for y in 0..height {
+
+            let reversed = serpentine && y % 2 == 1;
+            let dx: i32 = if reversed { -1 } else { 1 };
+            let xs: Box<dyn Iterator<Item = u32>> = if reversed {
+                Box::new((0..width).rev())
+            } else {
+                Box::new(0..width)
+            };
+
+            for x in xs {
+
+                let idx = (y * width + x) as usize;
+                let old = buf[idx];
+                let new = if old < threshold { 0.0 } else { 1.0 };
+                image.put_pixel(x, y, image::Luma([(new * 255.0) as u8]));
+
+                let err = old - new * 255.0;
+                diffuse(&mut buf, width, height, x as i32 + dx, y as i32, err * 7.0 / 16.0);
+                diffuse(&mut buf, width, height, x as i32 - dx, y as i32 + 1, err * 3.0 / 16.0);
+                diffuse(&mut buf, width, height, x as i32, y as i32 + 1, err * 5.0 / 16.0);
+                diffuse(&mut buf, width, height, x as i32 + dx, y as i32 + 1, err * 1.0 / 16.0);
+
+            }
+
+        }
 
     }
 
     println!("Saving image");
     println!("  Path: {out_path:?}");
-    image.save(&out_path)?;
+    // Dithered pixels are always exactly 0 or 255, so any cutoff strictly
+    // between them packs identically; 128 is as good as any.
+    save_gray_png(&image, &out_path, bit_depth, png_compression, png_filter, 128)?;
+
+    Ok(())
+
+}
+
+/// Add `amount` to the buffer at `(x, y)`; does nothing when `(x, y)` lies
+/// outside the image, so error pushed past the borders is dropped.
+fn diffuse(buf: &mut [f32], width: u32, height: u32, x: i32, y: i32, amount: f32) {
+    if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+        return;
+    }
+    buf[(y as u32 * width + x as u32) as usize] += amount;
+}
+
+/// Resolve the `PATH` argument into the list of files a batch run should
+/// process. `glob_pattern`, when given, takes priority (and stands alone,
+/// `in_path` may be absent) and is expanded with the `glob` crate;
+/// otherwise `in_path` must be a directory, expanded to every file directly
+/// inside it (non-recursive).
+fn collect_inputs(in_path: Option<&Path>, glob_pattern: Option<&str>) -> anyhow::Result<Vec<PathBuf>> {
+
+    if let Some(pattern) = glob_pattern {
+        let mut paths = Vec::new();
+        for entry in glob::glob(pattern)? {
+            paths.push(entry?);
+        }
+        paths.sort();
+        return Ok(paths);
+    }
+
+    let dir = in_path.expect("PATH is required unless --glob is given");
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+
+}
+
+/// Run `process` over every input path, bounded by `jobs` worker threads (0
+/// lets rayon pick one thread per available core), writing each output next
+/// to its source via `with_extension(out_extension)`. A per-file progress
+/// line is printed as results come in, one bad file does not abort the
+/// batch, and a final success/failure summary is printed once it's done.
+/// Returns an error if every file in the batch failed, so unattended/scripted
+/// callers see a non-zero exit instead of a silent total failure.
+fn run_batch<F>(inputs: &[PathBuf], out_extension: &str, jobs: usize, process: F) -> anyhow::Result<()>
+where
+    F: Fn(&Path, &Path) -> anyhow::Result<()> + Sync,
+{
+
+    println!("Batch processing {} image(s)...", inputs.len());
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+
+    let failures: Vec<(&PathBuf, anyhow::Error)> = pool.install(|| {
+        inputs.par_iter()
+            .filter_map(|in_path| {
+                let out_path = in_path.with_extension(out_extension);
+                match process(in_path, &out_path) {
+                    Ok(()) => {
+                        println!("  OK   {in_path:?} -> {out_path:?}");
+                        None
+                    }
+                    Err(err) => {
+                        println!("  FAIL {in_path:?}: {err}");
+                        Some((in_path, err))
+                    }
+                }
+            })
+            .collect()
+    });
+
+    println!(
+        "Batch done: {} succeeded, {} failed",
+        inputs.len() - failures.len(),
+        failures.len(),
+    );
+    for (in_path, err) in &failures {
+        println!("  {in_path:?}: {err}");
+    }
+
+    if !inputs.is_empty() && failures.len() == inputs.len() {
+        anyhow::bail!("all {} file(s) in the batch failed to process", inputs.len());
+    }
 
     Ok(())
 